@@ -8,10 +8,11 @@
 //!
 //!
 
-use super::{channel, Receiver, Sender};
+use super::{channel, Receiver, RecvError, Sender};
 use alloc::sync::Arc;
 use core::{
   pin::Pin,
+  sync::atomic::Ordering,
   task::{Context, Poll},
 };
 use futures_util::{stream::Stream, task::AtomicWaker};
@@ -22,7 +23,7 @@ pub fn async_channel<T: 'static>() -> (AsyncSender<T>, AsyncReceiver<T>) {
   let waker = Arc::new(AtomicWaker::new());
   (
     AsyncSender {
-      tx,
+      tx: Some(tx),
       waker: waker.clone(),
     },
     AsyncReceiver { rx, waker },
@@ -31,14 +32,16 @@ pub fn async_channel<T: 'static>() -> (AsyncSender<T>, AsyncReceiver<T>) {
 
 /// The sending part of a mpmc channel. This is used to send data through the channel to an receiver
 pub struct AsyncSender<T: 'static> {
-  tx: Sender<T>,
+  // kept as an `Option` so `Drop` can release it eagerly and observe whether this was the last
+  // sender before deciding to wake up a parked receiver
+  tx: Option<Sender<T>>,
   waker: Arc<AtomicWaker>,
 }
 
 impl<T: 'static> AsyncSender<T> {
   /// Send data through the channel
   pub fn send(&self, data: T) {
-    self.tx.send(data);
+    self.tx.as_ref().unwrap().send(data);
     self.waker.wake();
   }
 }
@@ -47,16 +50,31 @@ impl<T: 'static> AsyncSender<T> {
 impl<T: 'static> Clone for AsyncSender<T> {
   fn clone(&self) -> AsyncSender<T> {
     AsyncSender {
-      tx: self.tx.clone(),
+      tx: Some(self.tx.as_ref().unwrap().clone()),
       waker: self.waker.clone(),
     }
   }
 }
 
+/// Dropping the last [AsyncSender] needs to wake up a receiver that is currently parked in
+/// `poll_next` so it gets the chance to observe the channel as disconnected and terminate the
+/// stream with `None` instead of waiting forever.
+impl<T: 'static> Drop for AsyncSender<T> {
+  fn drop(&mut self) {
+    if let Some(tx) = self.tx.take() {
+      let senders = tx.senders.clone();
+      drop(tx);
+      if senders.load(Ordering::Acquire) == 0 {
+        self.waker.wake();
+      }
+    }
+  }
+}
+
 /// The receiving part of the channel. This can be used to retreive data that has been send from the sending part of it
 pub struct AsyncReceiver<T: 'static> {
-  rx: Receiver<T>,
-  waker: Arc<AtomicWaker>,
+  pub(super) rx: Receiver<T>,
+  pub(super) waker: Arc<AtomicWaker>,
 }
 
 /// Enable cloning the Sender so it can be used at different cores, filling up the same queue
@@ -75,12 +93,22 @@ impl<T: 'static> Stream for AsyncReceiver<T> {
   type Item = T;
 
   fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-    if let Ok(v) = self.rx.recv() {
-      Poll::Ready(Some(v))
-    } else {
-      // how get woken? This should be done by the sender
-      self.waker.register(cx.waker());
-      Poll::Pending
+    match self.rx.recv() {
+      Ok(v) => return Poll::Ready(Some(v)),
+      Err(RecvError::Disconnected) => return Poll::Ready(None),
+      Err(RecvError::Empty) => (),
+    }
+
+    // register the waker before re-checking so a `send`, or the last sender's `Drop`, racing with
+    // this poll always wakes a waker that has already been registered; otherwise one landing
+    // between the failed `recv` above and the `register` call would be missed and this stream
+    // would park forever
+    self.waker.register(cx.waker());
+
+    match self.rx.recv() {
+      Ok(v) => Poll::Ready(Some(v)),
+      Err(RecvError::Disconnected) => Poll::Ready(None),
+      Err(RecvError::Empty) => Poll::Pending,
     }
   }
 }
@@ -0,0 +1,209 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: MIT OR Apache License 2.0
+ **************************************************************************************************/
+
+//! # Bounded Multi Producer Multi Consumer Channel
+//!
+//! While the [super::Queue] based channel grows without limit, a constrained bare-metal device
+//! might not have the memory budget for an unbounded amount of in-flight messages. This module
+//! provides a fixed capacity ring buffer based channel that applies backpressure through
+//! [BoundedSender::try_send] instead of allocating without bounds.
+//!
+//! The ring is Dmitry Vyukov's bounded MPMC queue: every slot carries its own `sequence` number
+//! next to the boxed value, and a producer/consumer only ever acts on a slot once its sequence
+//! confirms the slot is actually free/filled for the position it reserved. This is what makes the
+//! ring safe for several [BoundedSender]s or [BoundedReceiver]s cloned across cores at once - a
+//! plain two-index CAS ring is not enough, as a consumer can advance `read` past a slot before it
+//! has actually taken the value out of it, letting a fast producer that laps the ring overwrite an
+//! item that has not been consumed yet.
+//!
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+  ptr,
+  sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// Create both sides of a bounded mpmc channel backed by a ring buffer of `capacity` slots, all of
+/// which are usable at the same time.
+///
+/// # Panics
+///
+/// Panics if `capacity` is `0`, as a channel without any slot can neither send nor receive.
+pub fn bounded_channel<T: 'static>(capacity: usize) -> (BoundedSender<T>, BoundedReceiver<T>) {
+  let queue = Arc::new(BoundedQueue::new(capacity));
+  (BoundedSender::new(queue.clone()), BoundedReceiver::new(queue))
+}
+
+/// A single ring buffer slot. `sequence` tracks which logical position (enqueue or dequeue) is
+/// currently allowed to touch `value`
+struct Slot<T: Sized + 'static> {
+  sequence: AtomicUsize,
+  value: AtomicPtr<T>,
+}
+
+/// The ring buffer backing a bounded channel
+struct BoundedQueue<T: Sized + 'static> {
+  /// fixed size storage for the boxed values currently in flight, guarded slot-by-slot through
+  /// each [Slot]'s `sequence`
+  list: Vec<Slot<T>>,
+  /// monotonically increasing position of the next slot a producer will reserve
+  enqueue_pos: AtomicUsize,
+  /// monotonically increasing position of the next slot a consumer will reserve
+  dequeue_pos: AtomicUsize,
+}
+
+impl<T: Sized + 'static> BoundedQueue<T> {
+  fn new(capacity: usize) -> Self {
+    assert!(
+      capacity > 0,
+      "bounded_channel requires a capacity of at least 1"
+    );
+    let list = (0..capacity)
+      .map(|i| Slot {
+        // a slot free for position `p` holds the sequence `2 * p` and a slot filled for position `p`
+        // holds `2 * p + 1`, so the two states stay distinct even with a single slot. Seed every
+        // slot as free for enqueue position `i`
+        sequence: AtomicUsize::new(i * 2),
+        value: AtomicPtr::new(ptr::null_mut()),
+      })
+      .collect();
+    Self {
+      list,
+      enqueue_pos: AtomicUsize::new(0),
+      dequeue_pos: AtomicUsize::new(0),
+    }
+  }
+
+  fn try_send(&self, value: T) -> Result<(), T> {
+    let len = self.list.len();
+    let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+    loop {
+      let slot = &self.list[pos % len];
+      let seq = slot.sequence.load(Ordering::Acquire);
+      // compute the difference via `wrapping_sub` on the unsigned positions before reinterpreting
+      // as signed: a plain `seq as isize - pos as isize` overflows (and panics in a debug build)
+      // once either position has wrapped around `usize::MAX`, which a long-running bare-metal
+      // system will eventually reach
+      let diff = seq.wrapping_sub(pos.wrapping_mul(2)) as isize;
+      if diff == 0 {
+        // this slot is free for position `pos`, try to claim it
+        match self
+          .enqueue_pos
+          .compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed)
+        {
+          Ok(_) => {
+            let node = Box::into_raw(Box::new(value));
+            slot.value.store(node, Ordering::Relaxed);
+            // publish the value and hand the slot to the consumer expecting dequeue position `pos`
+            slot.sequence.store(pos.wrapping_mul(2).wrapping_add(1), Ordering::Release);
+            return Ok(());
+          }
+          Err(actual) => pos = actual,
+        }
+      } else if diff < 0 {
+        // the slot is still waiting for the consumer at a position behind `pos`, the ring is full
+        return Err(value);
+      } else {
+        // another producer already claimed this position, reload and retry
+        pos = self.enqueue_pos.load(Ordering::Relaxed);
+      }
+    }
+  }
+
+  fn try_recv(&self) -> Result<T, ()> {
+    let len = self.list.len();
+    let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+    loop {
+      let slot = &self.list[pos % len];
+      let seq = slot.sequence.load(Ordering::Acquire);
+      let diff = seq.wrapping_sub(pos.wrapping_mul(2).wrapping_add(1)) as isize;
+      if diff == 0 {
+        // this slot has been filled for position `pos`, try to claim it
+        match self
+          .dequeue_pos
+          .compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed)
+        {
+          Ok(_) => {
+            let node = slot.value.swap(ptr::null_mut(), Ordering::Relaxed);
+            let value = unsafe { Box::from_raw(node) };
+            // free the slot for the producer that will eventually wrap back around to it
+            slot.sequence.store(pos.wrapping_add(len).wrapping_mul(2), Ordering::Release);
+            return Ok(*value);
+          }
+          Err(actual) => pos = actual,
+        }
+      } else if diff < 0 {
+        // the slot has not been filled yet for this position, the ring is empty
+        return Err(());
+      } else {
+        // another consumer already claimed this position, reload and retry
+        pos = self.dequeue_pos.load(Ordering::Relaxed);
+      }
+    }
+  }
+}
+
+impl<T: Sized + 'static> Drop for BoundedQueue<T> {
+  fn drop(&mut self) {
+    // dropping the queue means we need to drop all contained items as they have allocated memory
+    while self.try_recv().is_ok() {}
+  }
+}
+
+/// Sender of a bounded channel. In contrast to [super::Sender] sending could fail if the channel
+/// is currently full
+#[repr(C)]
+pub struct BoundedSender<T: 'static> {
+  inner: Arc<BoundedQueue<T>>,
+}
+
+#[doc(hidden)]
+unsafe impl<T> Send for BoundedSender<T> {}
+
+impl<T: 'static> BoundedSender<T> {
+  fn new(inner: Arc<BoundedQueue<T>>) -> Self {
+    BoundedSender { inner }
+  }
+
+  /// Try to send a value through the channel. If the channel is currently full the value is
+  /// handed back to the caller as part of the [Err] so no data is lost.
+  pub fn try_send(&self, value: T) -> Result<(), T> {
+    self.inner.try_send(value)
+  }
+}
+
+/// Enable cloning the Sender so it can be used at different cores, filling up the same queue
+impl<T: 'static> Clone for BoundedSender<T> {
+  fn clone(&self) -> BoundedSender<T> {
+    BoundedSender::new(self.inner.clone())
+  }
+}
+
+/// Receiver of a bounded channel.
+#[repr(C)]
+pub struct BoundedReceiver<T: 'static> {
+  inner: Arc<BoundedQueue<T>>,
+}
+
+impl<T: 'static> BoundedReceiver<T> {
+  fn new(inner: Arc<BoundedQueue<T>>) -> Self {
+    BoundedReceiver { inner }
+  }
+
+  /// Try to receive a value from the channel. Returns `Err(())` if the channel is currently empty
+  pub fn try_recv(&self) -> Result<T, ()> {
+    self.inner.try_recv()
+  }
+}
+
+/// Enable cloning the receiver so it can be used at different cores to receive data from the same
+/// queue
+impl<T: 'static> Clone for BoundedReceiver<T> {
+  fn clone(&self) -> BoundedReceiver<T> {
+    BoundedReceiver::new(self.inner.clone())
+  }
+}
@@ -10,17 +10,42 @@
 
 #[cfg(feature = "async")]
 mod r#async;
+mod bounded;
+#[cfg(feature = "async")]
+mod oneshot;
 mod queue;
+#[cfg(feature = "async")]
+mod select;
 
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use queue::*;
+use ruspiro_arch_aarch64::instructions::{dsb, sev, wfe};
 #[cfg(feature = "async")]
 pub use r#async::*;
+pub use bounded::*;
+#[cfg(feature = "async")]
+pub use oneshot::*;
+#[cfg(feature = "async")]
+pub use select::*;
 
 /// Create both sides of a mpmc channel
 pub fn channel<T: 'static>() -> (Sender<T>, Receiver<T>) {
   let queue = Arc::new(Queue::new());
-  (Sender::new(queue.clone()), Receiver::new(queue))
+  let senders = Arc::new(AtomicUsize::new(1));
+  (
+    Sender::new(queue.clone(), senders.clone()),
+    Receiver::new(queue, senders),
+  )
+}
+
+/// Error conditions that could occur while receiving from a [Receiver]
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvError {
+  /// The queue is currently empty but senders are still alive, retry later
+  Empty,
+  /// The queue is empty and every [Sender] has been dropped, no further data will ever arrive
+  Disconnected,
 }
 
 /// Sender that is using a queue to push messages/data that a receiver could work on
@@ -28,25 +53,39 @@ pub fn channel<T: 'static>() -> (Sender<T>, Receiver<T>) {
 #[repr(C)]
 pub struct Sender<T> {
   inner: Arc<Queue<T>>,
+  /// shared count of [Sender]s still alive, used by the [Receiver] to detect disconnection
+  senders: Arc<AtomicUsize>,
 }
 
 #[doc(hidden)]
 unsafe impl<T> Send for Sender<T> {}
 
 impl<T: 'static> Sender<T> {
-  pub fn new(inner: Arc<Queue<T>>) -> Self {
-    Sender { inner }
+  pub fn new(inner: Arc<Queue<T>>, senders: Arc<AtomicUsize>) -> Self {
+    Sender { inner, senders }
   }
 
   pub fn send(&self, data: T) {
-    self.inner.push(data)
+    self.inner.push(data);
+    // wake up any core currently parked in `wfe` inside `Receiver::recv_blocking`
+    dsb();
+    sev();
   }
 }
 
 /// Enable cloning the Sender so it can be used at different cores, filling up the same queue
 impl<T: 'static> Clone for Sender<T> {
   fn clone(&self) -> Sender<T> {
-    Sender::new(self.inner.clone())
+    self.senders.fetch_add(1, Ordering::AcqRel);
+    Sender::new(self.inner.clone(), self.senders.clone())
+  }
+}
+
+/// Dropping a [Sender] gives up its share of the channel. Once the last [Sender] is dropped the
+/// [Receiver] observes the queue as disconnected through [RecvError::Disconnected]
+impl<T> Drop for Sender<T> {
+  fn drop(&mut self) {
+    self.senders.fetch_sub(1, Ordering::AcqRel);
   }
 }
 
@@ -55,17 +94,47 @@ impl<T: 'static> Clone for Sender<T> {
 #[repr(C)]
 pub struct Receiver<T> {
   inner: Arc<Queue<T>>,
+  senders: Arc<AtomicUsize>,
 }
 
 impl<T: 'static> Receiver<T> {
-  pub fn new(inner: Arc<Queue<T>>) -> Self {
-    Receiver { inner }
+  pub fn new(inner: Arc<Queue<T>>, senders: Arc<AtomicUsize>) -> Self {
+    Receiver { inner, senders }
   }
 
-  pub fn recv(&self) -> Result<T, ()> {
+  /// Receive the next value from the queue. Returns [RecvError::Empty] if the queue is currently
+  /// empty but at least one [Sender] is still alive, or [RecvError::Disconnected] if the queue is
+  /// empty and every [Sender] has already been dropped.
+  pub fn recv(&self) -> Result<T, RecvError> {
     match self.inner.pop() {
       Pop::Data(v) => Ok(v),
-      Pop::Empty | Pop::Intermediate => Err(()),
+      Pop::Empty | Pop::Intermediate => {
+        if self.senders.load(Ordering::Acquire) == 0 {
+          // the last sender could have pushed right before it was dropped, between the failed
+          // `pop` above and the load of the sender count, so check the queue once more
+          match self.inner.pop() {
+            Pop::Data(v) => Ok(v),
+            Pop::Empty | Pop::Intermediate => Err(RecvError::Disconnected),
+          }
+        } else {
+          Err(RecvError::Empty)
+        }
+      }
+    }
+  }
+
+  /// Block the calling core until a value becomes available in the queue. Instead of busy-spinning
+  /// this parks the core with the ARMv8 `wfe` (wait-for-event) instruction between poll attempts, so an
+  /// idle core does not waste power. `wfe` consumes a previously latched event, so a `sev` issued by
+  /// [Sender::send] between the failed `pop` and the `wfe` is not lost. As `wfe` could also wake up
+  /// spuriously, or another core could have already consumed the pushed value, the queue is always
+  /// re-polled after waking up rather than assuming data is present.
+  pub fn recv_blocking(&self) -> T {
+    loop {
+      match self.inner.pop() {
+        Pop::Data(v) => return v,
+        Pop::Empty | Pop::Intermediate => wfe(),
+      }
     }
   }
 }
@@ -74,6 +143,6 @@ impl<T: 'static> Receiver<T> {
 /// queue
 impl<T: 'static> Clone for Receiver<T> {
   fn clone(&self) -> Receiver<T> {
-    Receiver::new(self.inner.clone())
+    Receiver::new(self.inner.clone(), self.senders.clone())
   }
 }
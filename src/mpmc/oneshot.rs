@@ -0,0 +1,164 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: MIT OR Apache License 2.0
+ **************************************************************************************************/
+
+//! # Oneshot Channel
+//!
+//! A lot of inter-core request/response patterns only ever need to hand over a single value once,
+//! e.g. "core 0 asks core 1 to compute X, core 1 returns one result". The reusable [super::Queue]
+//! based channel is overkill for this as it allocates per message and has no notion of completion.
+//! This module provides a single-value channel instead: the [OneshotSender] can be used exactly
+//! once and the [OneshotReceiver] resolves as a [Future] once the value arrives.
+//!
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+  future::Future,
+  pin::Pin,
+  ptr,
+  sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+  task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+
+/// Create both sides of a oneshot channel used to transfer a single value once between two cores.
+pub fn oneshot<T: 'static>() -> (OneshotSender<T>, OneshotReceiver<T>) {
+  let inner = Arc::new(Inner {
+    value: AtomicPtr::new(ptr::null_mut()),
+    closed: AtomicBool::new(false),
+    waker: AtomicWaker::new(),
+  });
+  (
+    OneshotSender {
+      inner: inner.clone(),
+    },
+    OneshotReceiver { inner },
+  )
+}
+
+struct Inner<T: 'static> {
+  /// the boxed value once [OneshotSender::send] has been called, stored as a raw pointer so it
+  /// could be moved between the sending and receiving side through an atomic operation
+  value: AtomicPtr<T>,
+  /// set when the [OneshotSender] has been dropped without sending a value
+  closed: AtomicBool,
+  /// wakes up the task currently parked in [OneshotReceiver]'s [Future] implementation
+  waker: AtomicWaker,
+}
+
+impl<T: 'static> Drop for Inner<T> {
+  fn drop(&mut self) {
+    // a value that was sent but never received (e.g. the [OneshotReceiver] was dropped first) is
+    // otherwise leaked, reclaim it here just like [super::Queue] does for its own nodes
+    let node = *self.value.get_mut();
+    if !node.is_null() {
+      unsafe {
+        drop(Box::from_raw(node));
+      }
+    }
+  }
+}
+
+/// Error returned from [OneshotReceiver] when the [OneshotSender] has been dropped without sending
+/// a value
+#[derive(Debug, PartialEq, Eq)]
+pub struct Canceled;
+
+/// Error returned from [OneshotReceiver::try_recv]
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+  /// the sender has not send a value yet
+  Empty,
+  /// the sender has been dropped without sending a value
+  Canceled,
+}
+
+/// The sending part of a oneshot channel
+pub struct OneshotSender<T: 'static> {
+  inner: Arc<Inner<T>>,
+}
+
+#[doc(hidden)]
+unsafe impl<T> Send for OneshotSender<T> {}
+
+impl<T: 'static> OneshotSender<T> {
+  /// Send the single value through the channel, consuming the sender as it could only be used once
+  pub fn send(self, value: T) {
+    let node = Box::into_raw(Box::new(value));
+    self.inner.value.store(node, Ordering::Release);
+    self.inner.waker.wake();
+    // letting `self` drop normally from here is harmless: `try_recv` always takes `value` before
+    // ever looking at `closed`, so the `Drop` impl below marking the (already delivered) channel
+    // as closed afterwards does not affect a receiver that already got its value
+  }
+}
+
+/// Dropping the [OneshotSender] marks the channel as canceled and wakes up a [OneshotReceiver]
+/// that is currently parked waiting for the value. If [OneshotSender::send] already ran this is a
+/// no-op as far as the receiver is concerned, since it only ever observes `closed` after finding
+/// no value.
+impl<T: 'static> Drop for OneshotSender<T> {
+  fn drop(&mut self) {
+    self.inner.closed.store(true, Ordering::Release);
+    self.inner.waker.wake();
+  }
+}
+
+/// The receiving part of a oneshot channel. Await it directly to receive the value, or use
+/// [OneshotReceiver::try_recv] to poll for it from a bare loop without a [core::task::Waker]
+pub struct OneshotReceiver<T: 'static> {
+  inner: Arc<Inner<T>>,
+}
+
+#[doc(hidden)]
+unsafe impl<T> Send for OneshotReceiver<T> {}
+
+impl<T: 'static> OneshotReceiver<T> {
+  /// Try to receive the value without registering a waker, useful when polling from a bare loop
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    let node = self.inner.value.swap(ptr::null_mut(), Ordering::Acquire);
+    if !node.is_null() {
+      let value = unsafe { Box::from_raw(node) };
+      return Ok(*value);
+    }
+
+    if self.inner.closed.load(Ordering::Acquire) {
+      // `send` could have stored the value right before the sender was dropped, between the swap
+      // above and the load of `closed`, so take the value once more before reporting cancellation
+      let node = self.inner.value.swap(ptr::null_mut(), Ordering::Acquire);
+      if !node.is_null() {
+        let value = unsafe { Box::from_raw(node) };
+        return Ok(*value);
+      }
+      Err(TryRecvError::Canceled)
+    } else {
+      Err(TryRecvError::Empty)
+    }
+  }
+}
+
+impl<T: 'static> Future for OneshotReceiver<T> {
+  type Output = Result<T, Canceled>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    match this.try_recv() {
+      Ok(v) => return Poll::Ready(Ok(v)),
+      Err(TryRecvError::Canceled) => return Poll::Ready(Err(Canceled)),
+      Err(TryRecvError::Empty) => (),
+    }
+
+    this.inner.waker.register(cx.waker());
+
+    // re-check after registering the waker so a `send`/`drop` racing with the registration above
+    // is not missed
+    match this.try_recv() {
+      Ok(v) => Poll::Ready(Ok(v)),
+      Err(TryRecvError::Canceled) => Poll::Ready(Err(Canceled)),
+      Err(TryRecvError::Empty) => Poll::Pending,
+    }
+  }
+}
@@ -12,13 +12,19 @@
 //! Elements are always pushed to the back of the queue and poped from the front thus providing a FIFO buffer
 //!
 //! The implementation tries to be lockfree and only working with atomic operations instead of Mutex or other
-//! data locks.
+//! data locks. It is based on the Vyukov non-intrusive MPSC node-queue: the list always starts with a dummy
+//! node whose value has already been taken, so a reader never has to chase a pointer to memory that has already
+//! been freed. The queue is seeded with a "stub" node as the first dummy; the stub is not kept, the first `pop`
+//! frees it and from then on the most recently consumed node acts as the dummy. As the core
+//! algorithm is only safe for a single consumer, concurrent [Queue::pop] calls from several [super::Receiver]s
+//! are serialized through a small spin-lock.
 //!
 
 use alloc::boxed::Box;
 use core::{
+  hint::spin_loop,
   ptr,
-  sync::atomic::{AtomicPtr, Ordering},
+  sync::atomic::{AtomicBool, AtomicPtr, Ordering},
 };
 use ruspiro_arch_aarch64::instructions::*;
 
@@ -28,16 +34,17 @@ use ruspiro_arch_aarch64::instructions::*;
 struct Node<T: Sized> {
   /// Pointer to the next node in the [Queue].
   next: AtomicPtr<Node<T>>,
-  /// The actually stored value of this node.
+  /// The actually stored value of this node. `None` for the initial stub node and for a node
+  /// that is currently acting as the new tail after its value has already been taken by [Queue::pop].
   value: Option<T>,
 }
 
 impl<T: Sized> Node<T> {
   /// create a new [Node] on the heap
-  fn new(value: T) -> Box<Node<T>> {
+  fn new(value: Option<T>) -> Box<Node<T>> {
     Box::new(Node {
       next: AtomicPtr::new(core::ptr::null_mut()),
-      value: Some(value),
+      value,
     })
   }
 }
@@ -57,96 +64,111 @@ pub struct Queue<T: Sized + 'static> {
   /// The head contains the pointer to the node that has been written last. Pushing to the queue will adjust
   /// the head.
   head: AtomicPtr<Node<T>>,
-  /// The tail contains the pointer to the node that need to be read first. Popping from the queue will adjust
-  /// tail
+  /// The tail contains the pointer to the node that has been consumed last (or the initial stub node before
+  /// the first `pop`). The actual next value to read lives in `tail.next`.
   tail: AtomicPtr<Node<T>>,
+  /// Serializes [pop] across concurrently cloned [super::Receiver]s as the underlying algorithm is only
+  /// single-consumer safe.
+  consumer_lock: AtomicBool,
 }
 
 impl<T: Sized + 'static> Queue<T> {
   /// create a new empty [Queue]
   #[allow(clippy::new_without_default)]
   pub fn new() -> Self {
-    // at the beginning of the lifetime of the queue the head and tail does not point anywhere
+    // seed the queue with a stub node so head and tail always point at connected, valid
+    // memory, even before the first value has been pushed
+    let stub = Box::into_raw(Node::new(None));
     Self {
-      head: AtomicPtr::new(ptr::null_mut()),
-      tail: AtomicPtr::new(ptr::null_mut()),
+      head: AtomicPtr::new(stub),
+      tail: AtomicPtr::new(stub),
+      consumer_lock: AtomicBool::new(false),
     }
   }
 
   /// Push a new element to the end of the [Queue]. The queue takes ownership of the value passed
   pub fn push(&self, value: T) {
     // 1. create a new node as raw pointer to ensure the node is not dropped when pushed to the queue
-    //    The heap will be freed when the node is popped and converted back into a Box using Box::from_raw
-    let node = Box::into_raw(Node::new(value));
-    // 2. exchange the head with the new node
+    //    The heap will be freed when the node is eventually consumed by `pop`
+    let node = Box::into_raw(Node::new(Some(value)));
+    // 2. exchange the head with the new node, this is the linearization point of `push`
     dmb();
-    let old_node = self.head.swap(node, Ordering::AcqRel);
+    let prev = self.head.swap(node, Ordering::AcqRel);
     dsb();
-    // 3. let the old node know it's next node.
-    if !old_node.is_null() {
-      unsafe {
-        (*old_node).next.store(node, Ordering::SeqCst);
-      }
+    // 3. link the previous head to the new node. Between step 2 and 3 a concurrent `pop` can observe
+    //    `prev.next` still being null even though `head != tail` - this is the transient state signalled
+    //    through `Pop::Intermediate`.
+    unsafe {
+      (*prev).next.store(node, Ordering::Release);
     }
-    // 4. if the tail is not yet pointing anywhere set the tail to the node just inserted
-    dmb();
-    // we can ignore the result of this operation. Err means the tail was alread set - so no need to update
-    let _ = self
-      .tail
-      .compare_exchange(ptr::null_mut(), node, Ordering::AcqRel, Ordering::Relaxed);
     dsb();
   }
 
   /// Pop an element from the top of the [Queue]
   pub fn pop(&self) -> Pop<T> {
-    // 1. swap the tail with an empty pointer to indicate nothing to read at the moment
-    dmb();
-    let node = self.tail.swap(ptr::null_mut(), Ordering::AcqRel);
-    dsb(); // from this moment all cores/thread accessing tail will see it as "empty"
-    if node.is_null() {
-      return Pop::Intermediate;
+    // acquire the consumer side spin-lock so at most one core runs the single-consumer algorithm below
+    // at any given time. This uses a plain busy-spin rather than `wfe`/`sev`: the latter share the same
+    // per-core event flag that `Sender::send` / `Receiver::recv_blocking` rely on, and a `sev` issued
+    // here on every unlock would immediately satisfy the `wfe` in `recv_blocking`'s empty-queue retry,
+    // turning its intended power-saving park into a full-speed busy loop.
+    while self
+      .consumer_lock
+      .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+      .is_err()
+    {
+      spin_loop();
     }
-    // 2. if the node we popped last is the one sitting on head we have processed all nodes thus require to
-    //    clean the head, otherwise dropping the node at the end of the pop would lead to access of freed memory
-    //    when a new node is pushed
-    // we can ignore the result of the operation as Err just indicates that we have not yet reached the HEAD and thus
-    // do not want to do anything
-    let _ = self
-      .head
-      .compare_exchange(node, ptr::null_mut(), Ordering::AcqRel, Ordering::Relaxed);
-    dsb();
 
-    // 3. re-construct the boxed node from the raw pointer
-    let node = unsafe { Box::from_raw(node) };
-
-    // 4. if this node has a follow-up node place this one into the tail
-    let next_node = node.next.load(Ordering::Acquire);
-    if !next_node.is_null() {
-      // TODO: Check if we need to handle the case where tail is not null at this moment. We kind of expect it to be
-      // null as we have swapped the current tail out and replaced with null at the beginning of the function. If the
-      // value would now be any different from null this may indicate an implementation issue as another core would have
-      // updated the tail (which should not happen) and thus the tail of the popped node will get lost.
-      let _ = self.tail.compare_exchange(
-        ptr::null_mut(),
-        next_node,
-        Ordering::AcqRel,
-        Ordering::Relaxed,
-      );
-      dsb(); // from this moment all cores/thread accessing the tail will see a proper node to pop
+    let result = self.pop_locked();
+
+    self.consumer_lock.store(false, Ordering::Release);
+
+    result
+  }
+
+  /// The actual single-consumer-safe pop implementation, only ever called while holding `consumer_lock`
+  fn pop_locked(&self) -> Pop<T> {
+    // 1. `tail` always points to a node whose value has already been taken (or the stub). The next value
+    //    to hand out lives in `tail.next`
+    let tail = self.tail.load(Ordering::Acquire);
+    let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+
+    if next.is_null() {
+      return if self.head.load(Ordering::Acquire) == tail {
+        // head and tail point to the same node, the queue is genuinely empty
+        Pop::Empty
+      } else {
+        // a push is currently in progress, `head` has been swapped but `prev.next` not yet stored
+        Pop::Intermediate
+      };
+    }
+
+    // 2. advance tail to the node we are about to hand out. From this point on `next` is reachable through
+    //    `tail` so it must never be freed, only the previously consumed node (the old `tail`) can be freed
+    self.tail.store(next, Ordering::Release);
+
+    // 3. take the value out of the new tail and free the now fully consumed, previous tail node. This never
+    //    touches memory that any other core could still be dereferencing as the list stayed connected the
+    //    whole time.
+    let value = unsafe { (*next).value.take() };
+    unsafe {
+      drop(Box::from_raw(tail));
     }
 
-    // 5. get the value from the node and return it
-    // if the node does not contain a value panicing is fine as this means the same node has been popped twice
-    // which is an implementation error
-    let value = node.value.unwrap();
-    Pop::Data(value)
+    // a value is always present here unless the same node gets popped twice, which is an implementation error
+    Pop::Data(value.unwrap())
   }
 }
 
 impl<T: Sized + 'static> Drop for Queue<T> {
   fn drop(&mut self) {
-    // dropping the queue means we need to drop all contained items
-    // as they have allocated memory
+    // dropping the queue means we need to drop all contained items as they have allocated memory
     while let Pop::Data(_) = self.pop() {}
+    // the remaining node referenced by `tail` (the stub, or the last consumed node) is never freed by `pop`
+    // as it always stays linked as the tail, free it now that the queue itself goes away
+    let tail = *self.tail.get_mut();
+    unsafe {
+      drop(Box::from_raw(tail));
+    }
   }
 }
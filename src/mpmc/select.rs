@@ -0,0 +1,70 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: MIT OR Apache License 2.0
+ **************************************************************************************************/
+
+//! # Select across multiple asynchronous channels
+//!
+//! Consumers that multiplex several channels, e.g. a control and a data queue, would otherwise
+//! need to poll each [AsyncReceiver] in an ad-hoc loop. [select] resolves as soon as any one of a
+//! slice of receivers has data, returning the index of the receiver together with the value.
+//!
+
+use super::AsyncReceiver;
+use core::{
+  future::Future,
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+/// Wait on multiple [AsyncReceiver]s at once. Resolves with the index of the first receiver that
+/// produced a value together with the value itself.
+///
+/// Fairness caveat: lower indices are checked first on every poll, so under sustained load on
+/// several receivers at once the lowest-indexed one could starve the others. Use [select_from] to
+/// start the scan at a different offset, e.g. round-robin across repeated calls, if this matters.
+pub fn select<T: 'static>(receivers: &mut [AsyncReceiver<T>]) -> SelectFuture<'_, T> {
+  select_from(receivers, 0)
+}
+
+/// Same as [select] but starts checking the receivers at `start` (wrapping around) instead of
+/// always starting at index `0`. Rotating the start offset between calls avoids starving
+/// higher-indexed channels under load.
+pub fn select_from<T: 'static>(receivers: &mut [AsyncReceiver<T>], start: usize) -> SelectFuture<'_, T> {
+  SelectFuture { receivers, start }
+}
+
+/// Future returned by [select] / [select_from]
+pub struct SelectFuture<'a, T: 'static> {
+  receivers: &'a mut [AsyncReceiver<T>],
+  start: usize,
+}
+
+impl<'a, T: 'static> Future for SelectFuture<'a, T> {
+  type Output = (usize, T);
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let len = this.receivers.len();
+
+    // register the waker with every receiver before scanning them: a send racing with this poll
+    // call always wakes a waker that has already been registered this way, otherwise a send that
+    // lands between the scan below and the registration would be missed and this future would
+    // never get re-polled
+    for receiver in this.receivers.iter() {
+      receiver.waker.register(cx.waker());
+    }
+
+    for offset in 0..len {
+      let i = (this.start + offset) % len;
+      if let Ok(v) = this.receivers[i].rx.recv() {
+        this.start = (i + 1) % len;
+        return Poll::Ready((i, v));
+      }
+    }
+
+    Poll::Pending
+  }
+}